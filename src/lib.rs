@@ -24,8 +24,10 @@
 //!
 //! # Platform support
 //!
-//! Only Windows, MacOS and Linux are supported. Other platforms don't do anything
-//! and will overflow your stack.
+//! Windows, MacOS, Linux, Android, the BSDs and Solaris/illumos are directly
+//! supported. Other platforms fall back to `RLIMIT_STACK`, and truly unknown
+//! platforms (or those where even that fails) don't do anything and will
+//! overflow your stack.
 
 #![allow(improper_ctypes)]
 
@@ -33,7 +35,10 @@
 extern crate cfg_if;
 extern crate libc;
 
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
+use std::ops::Range;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 extern {
     fn __stacker_stack_pointer() -> usize;
@@ -45,7 +50,68 @@ extern {
 thread_local! {
     static STACK_LIMIT: Cell<Option<usize>> = Cell::new(unsafe {
         guess_os_stack_limit()
-    })
+    });
+    static STACK_BASE: Cell<Option<usize>> = Cell::new(None);
+}
+
+// Guard-page ranges live in a small fixed-size table of atomics rather than
+// a thread-local `RefCell`, because the SIGSEGV/SIGBUS handler below must be
+// able to read them without allocating, taking a lock, or double-borrowing a
+// `RefCell` that's already mutably borrowed by the thread it just interrupted.
+//
+// Each slot's `state` moves 0 (free) -> 1 (claimed, being written) -> 2
+// (ready, `start`/`end` valid) -> back to 0 on release; a reader only trusts
+// `start`/`end` once it observes `state == 2`.
+const MAX_GUARD_RANGES: usize = 32;
+
+struct GuardSlot {
+    state: AtomicUsize,
+    start: AtomicUsize,
+    end: AtomicUsize,
+}
+
+impl GuardSlot {
+    const fn new() -> GuardSlot {
+        GuardSlot {
+            state: AtomicUsize::new(0),
+            start: AtomicUsize::new(0),
+            end: AtomicUsize::new(0),
+        }
+    }
+}
+
+static GUARD_RANGES: [GuardSlot; MAX_GUARD_RANGES] = [
+    GuardSlot::new(), GuardSlot::new(), GuardSlot::new(), GuardSlot::new(),
+    GuardSlot::new(), GuardSlot::new(), GuardSlot::new(), GuardSlot::new(),
+    GuardSlot::new(), GuardSlot::new(), GuardSlot::new(), GuardSlot::new(),
+    GuardSlot::new(), GuardSlot::new(), GuardSlot::new(), GuardSlot::new(),
+    GuardSlot::new(), GuardSlot::new(), GuardSlot::new(), GuardSlot::new(),
+    GuardSlot::new(), GuardSlot::new(), GuardSlot::new(), GuardSlot::new(),
+    GuardSlot::new(), GuardSlot::new(), GuardSlot::new(), GuardSlot::new(),
+    GuardSlot::new(), GuardSlot::new(), GuardSlot::new(), GuardSlot::new(),
+];
+
+/// Registers a guard page's `[start, end)` range so `in_guard_page` (which
+/// may run inside a signal handler) can recognize a fault inside it. Returns
+/// the claimed slot to release later via `unregister_guard_range`, or `None`
+/// if every slot is already in use, in which case this guard page just won't
+/// be recognized by the handler.
+fn register_guard_range(start: usize, end: usize) -> Option<usize> {
+    for (i, slot) in GUARD_RANGES.iter().enumerate() {
+        if slot.state.compare_exchange(0, 1, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+            slot.start.store(start, Ordering::SeqCst);
+            slot.end.store(end, Ordering::SeqCst);
+            slot.state.store(2, Ordering::SeqCst);
+            return Some(i);
+        }
+    }
+    None
+}
+
+fn unregister_guard_range(slot: Option<usize>) {
+    if let Some(i) = slot {
+        GUARD_RANGES[i].state.store(0, Ordering::SeqCst);
+    }
 }
 
 fn get_stack_limit() -> Option<usize> {
@@ -56,6 +122,55 @@ fn set_stack_limit(l: usize) {
     STACK_LIMIT.with(|s| s.set(Some(l)))
 }
 
+fn get_stack_base() -> Option<usize> {
+    STACK_BASE.with(|s| s.get())
+}
+
+fn set_stack_base(b: usize) {
+    STACK_BASE.with(|s| s.set(Some(b)))
+}
+
+fn clear_stack_base() {
+    STACK_BASE.with(|s| s.set(None))
+}
+
+/// Queries the full extent of the current (possibly grown) stack, as
+/// interpreted by this library: the lowest address still considered safe to
+/// use, through the highest address the stack was allocated from.
+///
+/// Returns `None` wherever `remaining_stack` would, plus on threads whose
+/// base this library was never able to determine, either by auto-detection
+/// or via `set_stack_bounds`.
+pub fn stack_bounds() -> Option<Range<usize>> {
+    match (get_stack_limit(), get_stack_base()) {
+        (Some(limit), Some(base)) => Some(limit..base),
+        _ => None,
+    }
+}
+
+/// Manually registers the current thread's real stack bounds, overriding the
+/// value this library would otherwise have auto-detected (or the lack
+/// thereof, on platforms `guess_os_stack_limit` doesn't support, and on
+/// threads `pthread_getattr_np` and friends can't reliably introspect).
+///
+/// `base` is the highest address of the thread's stack (where it started
+/// growing from) and `limit` is the lowest address still considered safe to
+/// use; `maybe_grow` will grow the stack once fewer than `red_zone` bytes
+/// remain between the current stack pointer and `limit`.
+///
+/// This is useful for embedders that know their thread's real stack extent
+/// out-of-band, such as threads created by C, green-thread runtimes, or
+/// unsupported OSes. It only affects the calling thread.
+pub fn set_stack_bounds(base: usize, limit: usize) {
+    // Setting the limit first forces STACK_LIMIT's lazy initializer (which
+    // calls guess_os_stack_limit) to run now if it hasn't already. On most
+    // platforms that initializer itself calls set_stack_base as a side
+    // effect; doing this before set_stack_base below makes sure our base
+    // is the one that sticks, instead of being clobbered by auto-detection.
+    set_stack_limit(limit);
+    set_stack_base(base);
+}
+
 /// Grows the call stack if necessary.
 ///
 /// This function is intended to be called at manually instrumented points in a
@@ -104,39 +219,220 @@ fn grow_the_stack<R, F: FnOnce() -> R>(stack_size: usize, f: F, remaining_stack_
 }
 
 unsafe fn _grow_the_stack(stack_size: usize, old_limit: usize, mut f: &mut FnMut()) {
-    // Align to 16-bytes (see below for why)
-    let stack_size = (stack_size + 15) / 16 * 16;
+    // Take a stack for ourselves, either reused from this thread's pool or
+    // freshly allocated, guarded on its low end by a page that faults on
+    // access instead of letting an overflow run off the end and corrupt the
+    // heap.
+    let stack = GrownStack::take(stack_size);
 
-    // Allocate some new stack for oureslves
-    let mut stack = Vec::<u8>::with_capacity(stack_size);
-    let new_limit = stack.as_ptr() as usize + 32 * 1024;
+    // Record this stack's guard page so the SIGSEGV/SIGBUS handler installed
+    // by `set_signal_handler` can recognize a fault inside it.
+    let (guard_start, guard_end) = stack.guard_range();
+    let guard_slot = register_guard_range(guard_start, guard_end);
 
     // Prepare stack limits for the stack switch
-    set_stack_limit(new_limit);
+    let old_base = get_stack_base();
+    set_stack_limit(stack.limit());
+    set_stack_base(stack.top());
 
     // Make sure the stack is 16-byte aligned which should be enough for all
     // platforms right now. Allocations on 64-bit are already 16-byte aligned
     // and our switching routine doesn't push any other data, but the routine on
     // 32-bit pushes an argument so we need a bit of an offset to get it 16-byte
-    // aligned when the call is made.
+    // aligned when the call is made. `stack.top()` itself is page-aligned
+    // (and pages are far larger than 16 bytes, so that alignment holds), but
+    // subtracting `offset` deliberately takes us 12 bytes off of it on
+    // 32-bit; it's `__stacker_switch_stacks`'s own argument push that brings
+    // the pointer back to 16-byte alignment by the time the closure runs.
     let offset = if cfg!(target_pointer_width = "32") {
         12
     } else {
         0
     };
-    __stacker_switch_stacks(stack.as_mut_ptr() as usize + stack_size - offset,
+    __stacker_switch_stacks(stack.top() - offset,
                             doit as usize as *const _,
                             &mut f as *mut &mut FnMut() as *mut u8);
 
     // Once we've returned reset bothe stack limits and then return value same
     // value the closure returned.
     set_stack_limit(old_limit);
+    match old_base {
+        Some(b) => set_stack_base(b),
+        None => clear_stack_base(),
+    }
+    unregister_guard_range(guard_slot);
+
+    // Hand the stack back to the pool instead of unmapping it, so a later
+    // `maybe_grow` on this thread can reuse it.
+    stack.release();
 
     unsafe extern fn doit(f: &mut &mut FnMut()) {
         f();
     }
 }
 
+// Stacks are pooled per size class rather than by their exact requested
+// size, so that callers who vary `stack_size` slightly between calls still
+// get to reuse allocations.
+const STACK_SIZE_CLASS_GRANULARITY: usize = 1024 * 1024;
+
+// How many unused stacks a single thread will hold onto per size class
+// before it just unmaps them.
+const MAX_POOLED_STACKS_PER_CLASS: usize = 4;
+
+fn stack_size_class(size: usize) -> usize {
+    (size + STACK_SIZE_CLASS_GRANULARITY - 1) / STACK_SIZE_CLASS_GRANULARITY *
+        STACK_SIZE_CLASS_GRANULARITY
+}
+
+thread_local! {
+    static STACK_POOL: RefCell<Vec<GrownStack>> = RefCell::new(Vec::new());
+}
+
+/// A heap-allocated stack with an inaccessible guard page mapped at its low
+/// end, so that overflowing the stack we switch to faults deterministically
+/// instead of silently corrupting the heap.
+struct GrownStack {
+    base: *mut u8,
+    len: usize,
+    class: usize,
+}
+
+impl GrownStack {
+    /// Allocates a stack with room for at least `size` bytes of usable space,
+    /// plus one inaccessible guard page below it.
+    fn new(size: usize) -> GrownStack {
+        unsafe {
+            let page_size = os_page_size();
+            let usable = (size + page_size - 1) / page_size * page_size;
+            let len = usable + page_size;
+            let base = map_stack(len);
+            protect_guard_page(base, page_size);
+            GrownStack { base: base, len: len, class: size }
+        }
+    }
+
+    /// Takes a stack of at least `stack_size` bytes from this thread's pool
+    /// of previously allocated, now-idle temporary stacks, falling back to a
+    /// fresh allocation on a miss.
+    fn take(stack_size: usize) -> GrownStack {
+        let class = stack_size_class(stack_size);
+        let pooled = STACK_POOL.with(|pool| {
+            let mut pool = pool.borrow_mut();
+            pool.iter().position(|s| s.class == class).map(|i| pool.remove(i))
+        });
+        pooled.unwrap_or_else(|| GrownStack::new(class))
+    }
+
+    /// Returns this stack to this thread's pool so a later `take` of the same
+    /// size class can reuse it, instead of unmapping it right away. Once the
+    /// pool for this size class is full, the stack is unmapped like normal.
+    fn release(self) {
+        STACK_POOL.with(|pool| {
+            let mut pool = pool.borrow_mut();
+            let in_class = pool.iter().filter(|s| s.class == self.class).count();
+            if in_class < MAX_POOLED_STACKS_PER_CLASS {
+                pool.push(self);
+            }
+        });
+    }
+
+    /// The address of the lowest byte of usable (non-guard) stack space. This
+    /// is used as the new stack limit, just above the guard page.
+    fn limit(&self) -> usize {
+        self.base as usize + unsafe { os_page_size() }
+    }
+
+    /// The address one past the end of the stack, used as the initial stack
+    /// pointer when switching onto it.
+    fn top(&self) -> usize {
+        self.base as usize + self.len
+    }
+
+    /// The `[start, end)` address range covered by this stack's guard page.
+    fn guard_range(&self) -> (usize, usize) {
+        (self.base as usize, self.limit())
+    }
+}
+
+impl Drop for GrownStack {
+    fn drop(&mut self) {
+        unsafe {
+            unmap_stack(self.base, self.len);
+        }
+    }
+}
+
+cfg_if! {
+    if #[cfg(windows)] {
+        const MEM_COMMIT: u32 = 0x1000;
+        const MEM_RESERVE: u32 = 0x2000;
+        const MEM_RELEASE: u32 = 0x8000;
+        const PAGE_READWRITE: u32 = 0x04;
+        const PAGE_NOACCESS: u32 = 0x01;
+
+        extern "system" {
+            fn VirtualAlloc(lpAddress: *mut u8,
+                             dwSize: usize,
+                             flAllocationType: u32,
+                             flProtect: u32) -> *mut u8;
+            fn VirtualProtect(lpAddress: *mut u8,
+                               dwSize: usize,
+                               flNewProtect: u32,
+                               lpflOldProtect: *mut u32) -> i32;
+            fn VirtualFree(lpAddress: *mut u8, dwSize: usize, dwFreeType: u32) -> i32;
+        }
+
+        // The page size used for memory protection purposes is always 4KiB on
+        // Windows, regardless of the allocation granularity.
+        unsafe fn os_page_size() -> usize {
+            4096
+        }
+
+        unsafe fn map_stack(len: usize) -> *mut u8 {
+            let base = VirtualAlloc(ptr::null_mut(), len, MEM_COMMIT | MEM_RESERVE, PAGE_READWRITE);
+            assert!(!base.is_null(), "failed to allocate a temporary stack");
+            base
+        }
+
+        unsafe fn protect_guard_page(base: *mut u8, guard_len: usize) {
+            let mut old_protect = 0;
+            let rc = VirtualProtect(base, guard_len, PAGE_NOACCESS, &mut old_protect);
+            assert!(rc != 0, "failed to protect the guard page of a temporary stack");
+        }
+
+        unsafe fn unmap_stack(base: *mut u8, _len: usize) {
+            let rc = VirtualFree(base, 0, MEM_RELEASE);
+            assert!(rc != 0, "failed to free a temporary stack");
+        }
+    } else {
+        unsafe fn os_page_size() -> usize {
+            libc::sysconf(libc::_SC_PAGESIZE) as usize
+        }
+
+        unsafe fn map_stack(len: usize) -> *mut u8 {
+            let base = libc::mmap(ptr::null_mut(),
+                                   len,
+                                   libc::PROT_READ | libc::PROT_WRITE,
+                                   libc::MAP_PRIVATE | libc::MAP_ANON,
+                                   -1,
+                                   0);
+            assert!(base != libc::MAP_FAILED, "failed to allocate a temporary stack");
+            base as *mut u8
+        }
+
+        unsafe fn protect_guard_page(base: *mut u8, guard_len: usize) {
+            let rc = libc::mprotect(base as *mut libc::c_void, guard_len, libc::PROT_NONE);
+            assert_eq!(rc, 0, "failed to protect the guard page of a temporary stack");
+        }
+
+        unsafe fn unmap_stack(base: *mut u8, len: usize) {
+            let rc = libc::munmap(base as *mut libc::c_void, len);
+            assert_eq!(rc, 0, "failed to free a temporary stack");
+        }
+    }
+}
+
 cfg_if! {
     if #[cfg(windows)] {
         // See this for where all this logic is coming from.
@@ -156,12 +452,17 @@ cfg_if! {
                 fn get_tib_address() -> *const usize;
             }
             // https://en.wikipedia.org/wiki/Win32_Thread_Information_Block for
-            // the struct layout of the 32-bit TIB. It looks like the struct
-            // layout of the 64-bit TIB is also the same for getting the stack
-            // limit: http://doxygen.reactos.org/d3/db0/structNT__TIB64.html
+            // the struct layout of the 32-bit TIB: offset 1 is the stack
+            // base, offset 2 the stack limit. It looks like the struct
+            // layout of the 64-bit TIB is also the same for getting these:
+            // http://doxygen.reactos.org/d3/db0/structNT__TIB64.html
+            set_stack_base(*get_tib_address().offset(1));
             Some(*get_tib_address().offset(2))
         }
-    } else if #[cfg(target_os = "linux")] {
+    } else if #[cfg(any(target_os = "linux", target_os = "android"))] {
+        // Android shares glibc/bionic's `pthread_getattr_np` here and is
+        // handled exactly like Linux: it reports the thread's real bounds,
+        // and no Android-specific minimum size or guard adjustment applies.
         use std::mem;
 
         unsafe fn guess_os_stack_limit() -> Option<usize> {
@@ -174,18 +475,360 @@ cfg_if! {
             assert_eq!(libc::pthread_attr_getstack(&attr, &mut stackaddr,
                                                    &mut stacksize), 0);
             assert_eq!(libc::pthread_attr_destroy(&mut attr), 0);
+            set_stack_base(stackaddr as usize + stacksize);
             Some(stackaddr as usize)
         }
     } else if #[cfg(target_os = "macos")] {
-        use libc::{c_void, pthread_t, size_t};
+        unsafe fn guess_os_stack_limit() -> Option<usize> {
+            let base = libc::pthread_get_stackaddr_np(libc::pthread_self()) as usize;
+            let size = libc::pthread_get_stacksize_np(libc::pthread_self()) as usize;
+            set_stack_base(base);
+            Some(base - size)
+        }
+    } else if #[cfg(any(target_os = "freebsd",
+                         target_os = "openbsd",
+                         target_os = "netbsd",
+                         target_os = "dragonfly"))] {
+        use std::mem;
 
         unsafe fn guess_os_stack_limit() -> Option<usize> {
-            Some(libc::pthread_get_stackaddr_np(libc::pthread_self()) as usize -
-                libc::pthread_get_stacksize_np(libc::pthread_self()) as usize)
+            let mut attr: libc::pthread_attr_t = mem::zeroed();
+            assert_eq!(libc::pthread_attr_init(&mut attr), 0);
+            assert_eq!(libc::pthread_attr_get_np(libc::pthread_self(), &mut attr), 0);
+            let mut stackaddr = 0 as *mut _;
+            let mut stacksize = 0;
+            assert_eq!(libc::pthread_attr_getstack(&attr, &mut stackaddr,
+                                                   &mut stacksize), 0);
+            assert_eq!(libc::pthread_attr_destroy(&mut attr), 0);
+            set_stack_base(stackaddr as usize + stacksize);
+            Some(stackaddr as usize)
+        }
+    } else if #[cfg(any(target_os = "solaris", target_os = "illumos"))] {
+        use std::mem;
+
+        unsafe fn guess_os_stack_limit() -> Option<usize> {
+            // `thr_stksegment` fills in the high end of the stack (`ss_sp`)
+            // and its size, unlike the `pthread_attr_getstack` platforms
+            // above which hand back the low end directly.
+            let mut segment: libc::stack_t = mem::zeroed();
+            assert_eq!(libc::thr_stksegment(&mut segment), 0);
+            set_stack_base(segment.ss_sp as usize);
+            Some(segment.ss_sp as usize - segment.ss_size)
         }
     } else {
+        use std::mem;
+
         unsafe fn guess_os_stack_limit() -> Option<usize> {
-            None
+            // No reliable direct query exists for this platform, and there's
+            // no portable way to find the thread's real stack base either.
+            // This falls back to the process' stack rlimit combined with
+            // whatever the stack pointer happens to be the first time this
+            // runs (driven by the STACK_LIMIT thread-local's lazy init, i.e.
+            // wherever the first maybe_grow/remaining_stack call lands) as a
+            // stand-in for the stack's high end. That's only a good estimate
+            // if this first call happens near the thread's entry point; if
+            // it happens deep in the call stack instead, the computed limit
+            // can sit far below the true one, and this library may fail to
+            // grow the stack before the real, unguarded stack overflows.
+            // Best-effort and unreliable: callers who know their thread's
+            // real extent should set it explicitly with `set_stack_bounds`.
+            let mut rlim: libc::rlimit = mem::zeroed();
+            if libc::getrlimit(libc::RLIMIT_STACK, &mut rlim) != 0 {
+                return None;
+            }
+            if rlim.rlim_cur == libc::RLIM_INFINITY {
+                return None;
+            }
+            let size = ::std::cmp::max(rlim.rlim_cur as usize, DEFAULT_MIN_STACK_SIZE);
+            let sp = __stacker_stack_pointer();
+            set_stack_base(sp);
+            Some(sp - size)
+        }
+    }
+}
+
+// Conservative floor used when a platform's reported stack rlimit is
+// implausibly small, so that the computed
+// limit doesn't leave too little headroom for `maybe_grow` to be useful.
+// Only the `RLIMIT_STACK` fallback above uses this, so it's dead code on
+// platforms with a direct query.
+#[allow(dead_code)]
+const DEFAULT_MIN_STACK_SIZE: usize = 64 * 1024;
+
+cfg_if! {
+    if #[cfg(windows)] {
+        const EXCEPTION_CONTINUE_SEARCH: i32 = 0;
+        const EXCEPTION_ACCESS_VIOLATION: u32 = 0xc0000005;
+
+        #[repr(C)]
+        struct ExceptionRecord {
+            exception_code: u32,
+            exception_flags: u32,
+            exception_record: *mut ExceptionRecord,
+            exception_address: *mut u8,
+            number_parameters: u32,
+            exception_information: [usize; 15],
+        }
+
+        #[repr(C)]
+        struct ExceptionPointers {
+            exception_record: *mut ExceptionRecord,
+            context_record: *mut u8,
+        }
+
+        extern "system" {
+            fn AddVectoredExceptionHandler(first: u32,
+                                            handler: unsafe extern "system" fn(*mut ExceptionPointers) -> i32)
+                                            -> *mut u8;
+        }
+
+        /// Installs a vectored exception handler that recognizes an access
+        /// violation inside the guard page of a heap-allocated temporary
+        /// stack and reports it with a clear message instead of crashing
+        /// opaquely. Faults outside a known guard page are passed on to any
+        /// other registered handler.
+        ///
+        /// Safe to call more than once; later calls are no-ops.
+        pub fn set_signal_handler() {
+            static INIT: ::std::sync::Once = ::std::sync::ONCE_INIT;
+            INIT.call_once(|| unsafe {
+                let rc = AddVectoredExceptionHandler(1, vectored_handler);
+                assert!(!rc.is_null(), "failed to install the stack overflow exception handler");
+            });
+        }
+
+        unsafe extern "system" fn vectored_handler(info: *mut ExceptionPointers) -> i32 {
+            let record = &*(*info).exception_record;
+            if record.exception_code == EXCEPTION_ACCESS_VIOLATION && record.number_parameters >= 2 {
+                let addr = record.exception_information[1];
+                if in_guard_page(addr) {
+                    report_overflow_and_abort();
+                }
+            }
+            EXCEPTION_CONTINUE_SEARCH
+        }
+    } else {
+        use std::sync::Once;
+        use libc::{c_int, c_void};
+
+        static INIT: Once = Once::new();
+        static mut PREV_SIGSEGV: Option<libc::sigaction> = None;
+        static mut PREV_SIGBUS: Option<libc::sigaction> = None;
+
+        // Large enough for the handler to safely run, report, and abort.
+        const ALT_STACK_SIZE: usize = 64 * 1024;
+
+        thread_local! {
+            static ALT_STACK_INSTALLED: Cell<bool> = Cell::new(false);
+        }
+
+        /// Installs a `SIGSEGV`/`SIGBUS` handler that recognizes a fault
+        /// inside the guard page of a heap-allocated temporary stack and
+        /// reports it with a clear message instead of crashing opaquely.
+        /// Faults outside a known guard page are chained to whatever handler
+        /// was previously installed.
+        ///
+        /// This also installs an alternate signal stack for the calling
+        /// thread, since by the time the fault happens the thread's own stack
+        /// has no room left to run a handler on. Call this once per thread
+        /// that should get a clear overflow report; threads that never call
+        /// it keep whatever behavior they had before.
+        pub fn set_signal_handler() {
+            use std::mem;
+
+            install_alt_stack();
+            unsafe {
+                INIT.call_once(|| {
+                    let mut action: libc::sigaction = mem::zeroed();
+                    action.sa_sigaction = signal_handler as usize;
+                    action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+                    libc::sigemptyset(&mut action.sa_mask);
+
+                    let mut prev: libc::sigaction = mem::zeroed();
+                    assert_eq!(libc::sigaction(libc::SIGSEGV, &action, &mut prev), 0);
+                    PREV_SIGSEGV = Some(prev);
+
+                    let mut prev: libc::sigaction = mem::zeroed();
+                    assert_eq!(libc::sigaction(libc::SIGBUS, &action, &mut prev), 0);
+                    PREV_SIGBUS = Some(prev);
+                });
+            }
         }
+
+        fn install_alt_stack() {
+            use std::mem;
+
+            ALT_STACK_INSTALLED.with(|installed| unsafe {
+                if installed.get() {
+                    return;
+                }
+                let altstack = Box::into_raw(Box::new([0u8; ALT_STACK_SIZE]));
+                let mut stack: libc::stack_t = mem::zeroed();
+                stack.ss_sp = altstack as *mut c_void;
+                stack.ss_flags = 0;
+                stack.ss_size = ALT_STACK_SIZE;
+                assert_eq!(libc::sigaltstack(&stack, ptr::null_mut()), 0);
+                installed.set(true);
+            });
+        }
+
+        extern "C" fn signal_handler(signum: c_int, info: *mut libc::siginfo_t, ctx: *mut c_void) {
+            use std::mem;
+
+            let addr = unsafe { (*info).si_addr() as usize };
+            if in_guard_page(addr) {
+                report_overflow_and_abort();
+            }
+            unsafe {
+                let prev = if signum == libc::SIGSEGV { &PREV_SIGSEGV } else { &PREV_SIGBUS };
+                match *prev {
+                    Some(ref prev) if prev.sa_sigaction == libc::SIG_IGN => {
+                        // The previous disposition was to ignore the signal;
+                        // honor that instead of calling it as a function.
+                    }
+                    Some(ref prev) if prev.sa_sigaction == libc::SIG_DFL => {
+                        libc::signal(signum, libc::SIG_DFL);
+                        libc::raise(signum);
+                    }
+                    Some(ref prev) if prev.sa_flags & libc::SA_SIGINFO != 0 => {
+                        let chained: extern "C" fn(c_int, *mut libc::siginfo_t, *mut c_void) =
+                            mem::transmute(prev.sa_sigaction);
+                        chained(signum, info, ctx);
+                    }
+                    Some(ref prev) => {
+                        // Previously installed without SA_SIGINFO: its
+                        // sa_sigaction is really a plain sa_handler, which
+                        // takes only the signal number.
+                        let chained: extern "C" fn(c_int) = mem::transmute(prev.sa_sigaction);
+                        chained(signum);
+                    }
+                    None => {
+                        // No previous handler: restore the default
+                        // disposition and re-raise so the process dies the
+                        // way it would have without us.
+                        libc::signal(signum, libc::SIG_DFL);
+                        libc::raise(signum);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn in_guard_page(addr: usize) -> bool {
+    GUARD_RANGES.iter().any(|slot| {
+        slot.state.load(Ordering::SeqCst) == 2 &&
+            addr >= slot.start.load(Ordering::SeqCst) &&
+            addr < slot.end.load(Ordering::SeqCst)
+    })
+}
+
+cfg_if! {
+    if #[cfg(windows)] {
+        fn report_overflow_and_abort() -> ! {
+            unsafe {
+                let msg = b"thread has overflowed its stack\n";
+                // `STDERR_FILENO` isn't defined for the MSVC target (only
+                // the GNU one), so hardcode the well-known stderr fd 2; and
+                // unlike the Unix signature, this target's `libc::write`
+                // takes the length as a `c_uint`, not a `usize`.
+                libc::write(2, msg.as_ptr() as *const libc::c_void, msg.len() as libc::c_uint);
+                libc::abort();
+            }
+        }
+    } else {
+        fn report_overflow_and_abort() -> ! {
+            unsafe {
+                let msg = b"thread has overflowed its stack\n";
+                libc::write(libc::STDERR_FILENO, msg.as_ptr() as *const libc::c_void, msg.len());
+                libc::abort();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_stack_bounds_overrides_detection() {
+        // No other test in this module reads STACK_LIMIT/STACK_BASE, so
+        // overriding them here for this thread doesn't need restoring.
+        // The bounds are placed around this thread's real stack pointer so
+        // that `remaining_stack`'s subtraction can't underflow.
+        let sp = unsafe { __stacker_stack_pointer() };
+        let base = sp + 1024 * 1024;
+        let limit = sp - 1024 * 1024;
+        set_stack_bounds(base, limit);
+        assert_eq!(stack_bounds(), Some(limit..base));
+        // `remaining_stack` reads the stack pointer from its own, slightly
+        // deeper frame, so it sees a little less than `sp - limit`.
+        let remaining = remaining_stack().expect("bounds were just set");
+        assert!(remaining <= sp - limit && remaining > sp - limit - 4096);
+    }
+
+    #[test]
+    fn size_class_rounds_up_to_granularity() {
+        assert_eq!(stack_size_class(1), STACK_SIZE_CLASS_GRANULARITY);
+        assert_eq!(stack_size_class(STACK_SIZE_CLASS_GRANULARITY), STACK_SIZE_CLASS_GRANULARITY);
+        assert_eq!(stack_size_class(STACK_SIZE_CLASS_GRANULARITY + 1),
+                   2 * STACK_SIZE_CLASS_GRANULARITY);
+    }
+
+    #[test]
+    fn grown_stack_offsets_are_consistent() {
+        let stack = GrownStack::new(64 * 1024);
+        let page_size = unsafe { os_page_size() };
+        assert_eq!(stack.limit(), stack.base as usize + page_size);
+        assert_eq!(stack.top(), stack.base as usize + stack.len);
+        assert!(stack.top() > stack.limit());
+        assert_eq!(stack.guard_range(), (stack.base as usize, stack.limit()));
+    }
+
+    #[test]
+    fn pool_reuses_released_stack() {
+        let class = stack_size_class(1);
+        STACK_POOL.with(|pool| pool.borrow_mut().retain(|s| s.class != class));
+
+        let first = GrownStack::take(1);
+        let base = first.base;
+        first.release();
+
+        let second = GrownStack::take(1);
+        assert_eq!(second.base, base, "a pooled stack should be reused rather than reallocated");
+        second.release();
+
+        STACK_POOL.with(|pool| pool.borrow_mut().retain(|s| s.class != class));
+    }
+
+    #[test]
+    fn pool_caps_stacks_per_size_class() {
+        let class = stack_size_class(1);
+        STACK_POOL.with(|pool| pool.borrow_mut().retain(|s| s.class != class));
+
+        let stacks: Vec<GrownStack> = (0..MAX_POOLED_STACKS_PER_CLASS + 2)
+            .map(|_| GrownStack::new(class))
+            .collect();
+        for stack in stacks {
+            stack.release();
+        }
+
+        STACK_POOL.with(|pool| {
+            let in_class = pool.borrow().iter().filter(|s| s.class == class).count();
+            assert_eq!(in_class, MAX_POOLED_STACKS_PER_CLASS);
+        });
+
+        STACK_POOL.with(|pool| pool.borrow_mut().retain(|s| s.class != class));
+    }
+
+    #[test]
+    fn guard_range_registration_round_trips() {
+        let slot = register_guard_range(0x1000, 0x2000).expect("a free slot");
+        assert!(in_guard_page(0x1500));
+        assert!(!in_guard_page(0x2000));
+        assert!(!in_guard_page(0xfff));
+        unregister_guard_range(Some(slot));
+        assert!(!in_guard_page(0x1500));
     }
 }